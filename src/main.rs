@@ -1,13 +1,75 @@
 use clap::Parser;
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::mpsc::channel;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::mpsc::{channel, RecvTimeoutError};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
-use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use ignore::gitignore::{gitconfig_excludes_path, Gitignore, GitignoreBuilder};
+use notify::{watcher, DebouncedEvent, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
 use regex::Regex;
 
+/// How long to wait after sending SIGTERM before giving up and sending SIGKILL.
+const RESTART_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// `notify::Watcher` requires `Self: Sized`, so it can't be used as a trait object directly; this
+/// is the subset of it we actually need, so `main` can stay agnostic to which backend is running.
+trait FsWatcher {
+    fn watch_path(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()>;
+    fn unwatch_path(&mut self, path: &Path) -> notify::Result<()>;
+}
+
+impl FsWatcher for RecommendedWatcher {
+    fn watch_path(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
+        Watcher::watch(self, path, recursive_mode)
+    }
+
+    fn unwatch_path(&mut self, path: &Path) -> notify::Result<()> {
+        Watcher::unwatch(self, path)
+    }
+}
+
+impl FsWatcher for PollWatcher {
+    fn watch_path(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
+        Watcher::watch(self, path, recursive_mode)
+    }
+
+    fn unwatch_path(&mut self, path: &Path) -> notify::Result<()> {
+        Watcher::unwatch(self, path)
+    }
+}
+
+/// Selects which `notify` backend to build. Native watchers (inotify/FSEvents/etc.) are the
+/// default, but they don't work reliably over NFS, SMB, or some Docker bind mounts, so `--poll`
+/// swaps in notify's `PollWatcher` instead.
+enum WatcherBackend {
+    Native,
+    Poll(Duration),
+}
+
+impl WatcherBackend {
+    fn create(
+        &self,
+        tx: std::sync::mpsc::Sender<DebouncedEvent>,
+        debounce_interval: Duration,
+    ) -> Result<Box<dyn FsWatcher>> {
+        match self {
+            WatcherBackend::Native => {
+                let watcher = watcher(tx, debounce_interval)
+                    .context("Unable to create native filesystem watcher")?;
+                Ok(Box::new(watcher))
+            }
+            WatcherBackend::Poll(poll_interval) => {
+                let watcher = PollWatcher::new(tx, *poll_interval)
+                    .context("Unable to create polling filesystem watcher")?;
+                Ok(Box::new(watcher))
+            }
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[clap(
     version,
@@ -28,43 +90,343 @@ struct Args {
     /// Regex pattern to exclude from watch
     #[clap(short, long)]
     exclude: Option<String>,
+
+    /// Treat the command as a long-running process: kill and respawn it on every qualifying
+    /// change instead of spawning a new copy alongside it
+    #[clap(short, long)]
+    restart: bool,
+
+    /// Don't filter out paths matched by .gitignore/.ignore files in the watched directory or its
+    /// ancestors, or by the global git excludes file
+    #[clap(long)]
+    no_ignore: bool,
+
+    /// Comma-separated list of file extensions to watch (e.g. "js,css,html"); when given, only
+    /// paths with one of these extensions trigger the command
+    #[clap(short = 'e', long, value_delimiter = ',')]
+    exts: Vec<String>,
+
+    /// Poll for changes every <POLL> milliseconds instead of using the native filesystem watcher.
+    /// Use this on NFS, SMB, or container bind mounts where native watchers are unreliable
+    #[clap(long, value_name = "POLL")]
+    poll: Option<u64>,
+
+    /// Clear the terminal before each command invocation
+    #[clap(short, long)]
+    clear: bool,
+
+    /// Print diagnostics about change events, including ones filtered out
+    #[clap(short, long)]
+    verbose: bool,
 }
 
-// filters event and exclude regex to determine if the command needs to be run
-fn should_run_command(event: &DebouncedEvent, exclude: &Option<Regex>) -> bool {
+/// Walks up from `root` through every ancestor directory collecting `.gitignore` and `.ignore`
+/// rules, plus the user's global excludes file (`core.excludesFile`, or the XDG default), into a
+/// single matcher. Errors reading any individual file are ignored, matching how `ignore` itself
+/// treats unreadable ignore files during a directory walk.
+fn build_ignore_matcher(root: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+
+    if let Some(global) = gitconfig_excludes_path() {
+        builder.add(global);
+    }
+
+    // `GitignoreBuilder` gives precedence to whichever matching glob was added last, so add
+    // ancestors outermost-first: `root` itself goes last and its rules win, matching how git
+    // itself lets the nearer `.gitignore` override a parent directory's
+    for ancestor in root.ancestors().collect::<Vec<_>>().into_iter().rev() {
+        builder.add(ancestor.join(".gitignore"));
+        builder.add(ancestor.join(".ignore"));
+    }
+
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+// the path an event is about, for the event kinds that can trigger the command; every other kind
+// yields None, including Write, Chmod and Remove (writes and removals are picked up from their
+// NoticeWrite/NoticeRemove instead) and Error, Rescan, etc.
+fn event_path(event: &DebouncedEvent) -> Option<&PathBuf> {
+    match event {
+        DebouncedEvent::NoticeWrite(path) => Some(path),
+        DebouncedEvent::Create(path) => Some(path),
+        DebouncedEvent::NoticeRemove(path) => Some(path),
+        DebouncedEvent::Rename(_, path) => Some(path),
+        _ => None,
+    }
+}
+
+// filters a changed path against the exclude regex, the gitignore matcher, and the extension
+// allowlist to determine if it should trigger the command
+fn should_run_command(
+    path: &Path,
+    exclude: &Option<Regex>,
+    ignore: &Gitignore,
+    exts: &HashSet<String>,
+) -> bool {
     // convert our path to a unicode string (not handling non-unicode chars), then match it against
     // our regex, if the regex exists, otherwise returning true if we don't have an exclusion
     // regex.
-    let matcher = |path: &PathBuf| -> bool {
-        exclude
-            .as_ref()
-            .map(|regex| !regex.is_match(&path.to_string_lossy()))
-            .unwrap_or(true)
-    };
+    let excluded_by_regex = exclude
+        .as_ref()
+        .map(|regex| regex.is_match(&path.to_string_lossy()))
+        .unwrap_or(false);
+
+    if excluded_by_regex {
+        return false;
+    }
+
+    if ignore
+        .matched_path_or_any_parents(path, path.is_dir())
+        .is_ignore()
+    {
+        return false;
+    }
+
+    if !exts.is_empty() {
+        let matches_ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| exts.contains(&ext.to_lowercase()))
+            .unwrap_or(false);
+
+        if !matches_ext {
+            return false;
+        }
+    }
+
+    true
+}
+
+// Re-resolves what we're watching in response to `event`, so that directories created after
+// startup get picked up and a watched root that's deleted and recreated doesn't silently stop
+// being watched. `watched_paths` tracks what we've explicitly added so we don't churn redundant
+// watch/unwatch calls on every event.
+//
+// This only applies to the native backend: `PollWatcher` re-walks each registered root from
+// scratch on every poll tick regardless of whether it currently exists, so it already picks up
+// new subdirectories and a recreated root on its own; adding our own watches on top of that would
+// just grow an ever-increasing set of redundant top-level entries for it to walk.
+#[allow(clippy::too_many_arguments)]
+fn resync_watch(
+    event: &DebouncedEvent,
+    watcher: &mut dyn FsWatcher,
+    watched_paths: &mut HashSet<PathBuf>,
+    pending_root_parent: &mut Option<PathBuf>,
+    root: &Path,
+    manual_resync: bool,
+    verbose: bool,
+) {
+    if !manual_resync {
+        return;
+    }
+
+    let rewatch =
+        |watcher: &mut dyn FsWatcher, path: &Path, watched_paths: &mut HashSet<PathBuf>| -> bool {
+            if watched_paths.contains(path) {
+                return true;
+            }
+
+            if !path.is_dir() {
+                return false;
+            }
+
+            match watcher.watch_path(path, RecursiveMode::Recursive) {
+                Ok(()) => {
+                    if verbose {
+                        println!("fswatch: now watching {}", path.display());
+                    }
+                    watched_paths.insert(path.to_path_buf());
+                    true
+                }
+                Err(e) => {
+                    eprintln!("fswatch: unable to watch {}: {}", path.display(), e);
+                    false
+                }
+            }
+        };
+
+    let unwatch =
+        |watcher: &mut dyn FsWatcher, path: &Path, watched_paths: &mut HashSet<PathBuf>| {
+            if watched_paths.remove(path) {
+                let _ = watcher.unwatch_path(path);
+                if verbose {
+                    println!("fswatch: stopped watching {}", path.display());
+                }
+            }
+        };
 
     match event {
-        DebouncedEvent::NoticeWrite(path) => matcher(path),
-        DebouncedEvent::Create(path) => matcher(path),
-        DebouncedEvent::NoticeRemove(path) => matcher(path),
-        DebouncedEvent::Rename(_, path) => matcher(path),
-        _ => false,
+        // the root is back: drop the parent fallback watch we took below while it was gone
+        DebouncedEvent::Create(path) if rewatch(watcher, path, watched_paths) && path == root => {
+            if let Some(parent) = pending_root_parent.take() {
+                unwatch(watcher, &parent, watched_paths);
+            }
+        }
+        DebouncedEvent::Create(_) => {}
+        DebouncedEvent::Remove(path) => {
+            unwatch(watcher, path, watched_paths);
+
+            if path == root && !rewatch(watcher, root, watched_paths) {
+                // the root doesn't exist yet (e.g. `rm -rf root && mkdir root` hasn't finished):
+                // inotify-style watches die with the directory, so watch the parent instead, just
+                // deep enough to notice when root itself reappears
+                if let Some(parent) = root.parent() {
+                    if rewatch_nonrecursive(watcher, parent, watched_paths, verbose) {
+                        *pending_root_parent = Some(parent.to_path_buf());
+                    }
+                }
+            }
+        }
+        DebouncedEvent::Rename(from, to) => {
+            unwatch(watcher, from, watched_paths);
+            rewatch(watcher, to, watched_paths);
+        }
+        _ => {}
+    }
+}
+
+fn rewatch_nonrecursive(
+    watcher: &mut dyn FsWatcher,
+    path: &Path,
+    watched_paths: &mut HashSet<PathBuf>,
+    verbose: bool,
+) -> bool {
+    if watched_paths.contains(path) {
+        return true;
+    }
+
+    match watcher.watch_path(path, RecursiveMode::NonRecursive) {
+        Ok(()) => {
+            if verbose {
+                println!("fswatch: watching {} until it reappears", path.display());
+            }
+            watched_paths.insert(path.to_path_buf());
+            true
+        }
+        Err(e) => {
+            eprintln!("fswatch: unable to watch {}: {}", path.display(), e);
+            false
+        }
     }
 }
 
-fn handle_file_change(event: DebouncedEvent, command: &[String], exclude: &Option<Regex>) {
-    let run_command = should_run_command(&event, exclude);
+// adds `event`'s path to `changed_paths` if it passes our filters, for later batch dispatch
+fn collect_change(
+    event: &DebouncedEvent,
+    exclude: &Option<Regex>,
+    ignore: &Gitignore,
+    exts: &HashSet<String>,
+    verbose: bool,
+    changed_paths: &mut HashSet<PathBuf>,
+) {
+    let Some(path) = event_path(event) else {
+        return;
+    };
+
+    if should_run_command(path, exclude, ignore, exts) {
+        if verbose {
+            println!("fswatch: ChangeEvent: {:?}", event);
+        }
+        changed_paths.insert(path.clone());
+    } else if verbose {
+        println!("fswatch: ignored due to filter: {:?}", event);
+    }
+}
+
+// runs `command` once for a whole batch of coalesced changes, exporting the changed paths so the
+// command can act on precisely what changed instead of re-scanning everything itself
+fn handle_changed_paths(
+    changed_paths: &HashSet<PathBuf>,
+    root: &str,
+    command: &[String],
+    restart: bool,
+    clear: bool,
+    verbose: bool,
+    child: &mut Option<Child>,
+) {
+    if changed_paths.is_empty() {
+        return;
+    }
+
+    if verbose {
+        println!(
+            "fswatch: {} path(s) changed, running command",
+            changed_paths.len()
+        );
+    }
 
-    if run_command {
-        println!("fswatch: ChangeEvent: {:?}", &event);
-        let result = Command::new(&command[0]).args(&command[1..]).spawn();
+    if clear {
+        // ANSI: clear screen, then move cursor to (1, 1). Flush explicitly since stdout is
+        // line-buffered and the spawned command below writes to the inherited stdout fd
+        // directly, which could otherwise interleave with or precede this sequence
+        print!("\x1B[2J\x1B[1;1H");
+        std::io::stdout().flush().ok();
+    }
 
-        match result {
-            Ok(..) => {}
-            Err(e) => eprintln!("fswatch: {} failed with error {}", command.join(" "), e),
+    if restart {
+        // we're supervising a long-running process: shut down the previous instance before
+        // spawning its replacement, otherwise we'd leak a process on every change
+        if let Some(mut previous) = child.take() {
+            terminate_child(&mut previous);
         }
+    } else if let Some(existing) = child {
+        // fire-and-forget mode: just reap the previous child if it already exited; one that is
+        // still running is replaced below without being waited on
+        let _ = existing.try_wait();
+    }
+
+    let changed_paths_env = changed_paths
+        .iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let result = Command::new(&command[0])
+        .args(&command[1..])
+        .env("FSWATCH_CHANGED_PATHS", changed_paths_env)
+        .env("FSWATCH_COMMON_PATH", root)
+        .spawn();
+
+    match result {
+        // track the spawned child even in fire-and-forget mode, so the next invocation can reap
+        // it via `try_wait` above if it has exited by then
+        Ok(new_child) => *child = Some(new_child),
+        Err(e) => eprintln!("fswatch: {} failed with error {}", command.join(" "), e),
     }
 }
 
+/// Asks `child` to shut down gracefully (SIGTERM on Unix), giving it `RESTART_GRACE_PERIOD` to
+/// exit on its own before escalating to SIGKILL.
+#[cfg(unix)]
+fn terminate_child(child: &mut Child) {
+    use std::time::Instant;
+
+    // Safety: `child.id()` is a valid pid for as long as `child` hasn't been waited on, which
+    // holds here since we only reap it below.
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+
+    let deadline = Instant::now() + RESTART_GRACE_PERIOD;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) | Err(_) => return,
+            Ok(None) if Instant::now() >= deadline => break,
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+
+    // the process ignored SIGTERM (or is otherwise stuck): force it
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+#[cfg(windows)]
+fn terminate_child(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
@@ -81,22 +443,195 @@ fn main() -> Result<()> {
     // Create a channel to receive the events.
     let (tx, rx) = channel();
 
-    // Create a watcher object, delivering debounced events.
-    // The notification back-end is selected based on the platform.
-    let mut watcher = watcher(tx, Duration::from_secs(2)).unwrap();
+    let debounce_interval = Duration::from_secs(args.debounce_interval.max(0) as u64);
+
+    // Create a watcher object, delivering debounced events. The backend is native by default,
+    // selected based on the platform, unless `--poll` asks for the polling backend instead.
+    let backend = match args.poll {
+        Some(poll_interval_ms) => WatcherBackend::Poll(Duration::from_millis(poll_interval_ms)),
+        None => WatcherBackend::Native,
+    };
+    // the poll backend already re-walks each registered root from scratch every tick, so it
+    // picks up new/recreated directories on its own; only the native backend needs our help
+    let manual_resync = matches!(backend, WatcherBackend::Native);
+    let mut watcher = backend.create(tx, debounce_interval)?;
+
+    // made absolute so it compares equal to the absolute paths in change events, and so the
+    // ignore-file walk reaches the real ancestor directories
+    let root_buf = std::env::current_dir()
+        .map(|cwd| cwd.join(&args.file_path))
+        .unwrap_or_else(|_| PathBuf::from(&args.file_path));
+    let root = root_buf.as_path();
 
     // Add a path to be watched. All files and directories at that path and
     // below will be monitored for changes.
     watcher
-        .watch(&args.file_path, RecursiveMode::Recursive)
+        .watch_path(root, RecursiveMode::Recursive)
         .with_context(|| format!("Unable to watch filepath {}", &args.file_path))?;
 
     println!("fswatch: watching {} for changes...", args.file_path);
+
+    // paths we've explicitly added a watch for, so Create/Remove/Rename handling doesn't churn
+    // redundant watch/unwatch calls
+    let mut watched_paths = HashSet::new();
+    watched_paths.insert(root.to_path_buf());
+
+    // set while we're watching the root's parent as a fallback because the root itself was
+    // deleted and didn't exist to re-watch yet; cleared once the root reappears
+    let mut pending_root_parent: Option<PathBuf> = None;
+
+    // gitignore/.ignore matcher for the watched root, unless the user opted out
+    let ignore_matcher = if args.no_ignore {
+        Gitignore::empty()
+    } else {
+        build_ignore_matcher(root)
+    };
+
+    // extension allowlist, lowercased once up front so matching is a cheap set lookup
+    let exts: HashSet<String> = args.exts.iter().map(|ext| ext.to_lowercase()).collect();
+
+    // holds the most recently spawned child, so `--restart` can tear it down before spawning its
+    // replacement and fire-and-forget mode can reap it once it has exited
+    let mut child: Option<Child> = None;
+
     // loop forever watching for change events
     loop {
-        match rx.recv() {
-            Ok(event) => handle_file_change(event, &args.command, &exclude_regex),
-            Err(e) => println!("fswatch: watcher error from channel: {:?}", e),
+        let first_event = match rx.recv() {
+            Ok(event) => event,
+            Err(e) => {
+                println!("fswatch: watcher error from channel: {:?}", e);
+                continue;
+            }
+        };
+
+        // a single edit often fans out into many raw events (save, git checkout, editor
+        // save-all), so we coalesce everything that arrives before the channel goes quiet for
+        // `debounce_interval` into one batch and run the command exactly once for all of it
+        let mut changed_paths = HashSet::new();
+        resync_watch(
+            &first_event,
+            watcher.as_mut(),
+            &mut watched_paths,
+            &mut pending_root_parent,
+            root,
+            manual_resync,
+            args.verbose,
+        );
+        collect_change(
+            &first_event,
+            &exclude_regex,
+            &ignore_matcher,
+            &exts,
+            args.verbose,
+            &mut changed_paths,
+        );
+
+        loop {
+            match rx.recv_timeout(debounce_interval) {
+                Ok(event) => {
+                    resync_watch(
+                        &event,
+                        watcher.as_mut(),
+                        &mut watched_paths,
+                        &mut pending_root_parent,
+                        root,
+                        manual_resync,
+                        args.verbose,
+                    );
+                    collect_change(
+                        &event,
+                        &exclude_regex,
+                        &ignore_matcher,
+                        &exts,
+                        args.verbose,
+                        &mut changed_paths,
+                    )
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
         }
+
+        handle_changed_paths(
+            &changed_paths,
+            &args.file_path,
+            &args.command,
+            args.restart,
+            args.clear,
+            args.verbose,
+            &mut child,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitignore_matching(root: &Path, pattern: &str) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(root);
+        builder.add_line(None, pattern).unwrap();
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn should_run_command_skips_files_inside_ignored_directories() {
+        let root = Path::new("/project");
+        let ignore = gitignore_matching(root, "target/");
+        let exts = HashSet::new();
+
+        assert!(!should_run_command(
+            &root.join("target/debug/foo"),
+            &None,
+            &ignore,
+            &exts,
+        ));
+        assert!(should_run_command(
+            &root.join("src/main.rs"),
+            &None,
+            &ignore,
+            &exts,
+        ));
+    }
+
+    #[test]
+    fn should_run_command_honors_exts_allowlist() {
+        let root = Path::new("/project");
+        let ignore = Gitignore::empty();
+        let exts: HashSet<String> = ["rs".to_string()].into_iter().collect();
+
+        assert!(should_run_command(
+            &root.join("src/main.rs"),
+            &None,
+            &ignore,
+            &exts,
+        ));
+        assert!(!should_run_command(
+            &root.join("README.md"),
+            &None,
+            &ignore,
+            &exts,
+        ));
+    }
+
+    #[test]
+    fn should_run_command_honors_exclude_regex() {
+        let root = Path::new("/project");
+        let ignore = Gitignore::empty();
+        let exts = HashSet::new();
+        let exclude = Some(Regex::new(r"\.log$").unwrap());
+
+        assert!(!should_run_command(
+            &root.join("app.log"),
+            &exclude,
+            &ignore,
+            &exts,
+        ));
+        assert!(should_run_command(
+            &root.join("src/main.rs"),
+            &exclude,
+            &ignore,
+            &exts,
+        ));
     }
 }